@@ -0,0 +1,137 @@
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc;
+use tracing::{info, error, debug};
+
+use crate::{Config, TelemetryMessage};
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertOp {
+    Gt,
+    Lt,
+}
+
+impl AlertOp {
+    fn breaches(self, value: f64, threshold: f64) -> bool {
+        match self {
+            AlertOp::Gt => value > threshold,
+            AlertOp::Lt => value < threshold,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlertRule {
+    pub device_id: Option<String>,
+    pub metric: String,
+    pub op: AlertOp,
+    pub value: f64,
+}
+
+impl AlertRule {
+    fn matches_device(&self, device_id: &str) -> bool {
+        self.device_id.as_deref().map_or(true, |d| d == device_id)
+    }
+
+    fn metric_value(&self, message: &TelemetryMessage) -> Option<f64> {
+        message.metrics.get(&self.metric).copied()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveAlert {
+    pub device_id: String,
+    pub metric: String,
+    pub value: f64,
+    pub threshold: f64,
+    pub fired_at: DateTime<Utc>,
+}
+
+/// Shared handle onto the currently-firing alerts, read by `GET /api/alerts` and written by
+/// `run`. Keyed by (device_id, rule index in `Config::alert_rules`) rather than
+/// (device_id, metric) -- two rules can watch the same metric (e.g. `temp > 40` and
+/// `temp < 10`), and each needs its own hysteresis state.
+pub type AlertState = Arc<RwLock<HashMap<(String, usize), ActiveAlert>>>;
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    device_id: &'a str,
+    metric: &'a str,
+    op: &'a str,
+    threshold: f64,
+    value: f64,
+    fired_at: DateTime<Utc>,
+}
+
+/// Evaluates each telemetry message against the configured threshold rules and fires a
+/// webhook the first time a rule's threshold is crossed. Hysteresis: a sustained breach only
+/// fires once, and must clear (stop breaching) before it can fire again.
+pub async fn run(config: Config, mut rx: mpsc::UnboundedReceiver<TelemetryMessage>, state: AlertState) {
+    info!("Alerting task started with {} rule(s)", config.alert_rules.len());
+
+    let client = reqwest::Client::new();
+
+    while let Some(message) = rx.recv().await {
+        for (rule_index, rule) in config.alert_rules.iter().enumerate() {
+            if !rule.matches_device(&message.device_id) {
+                continue;
+            }
+
+            let Some(value) = rule.metric_value(&message) else {
+                continue;
+            };
+
+            let key = (message.device_id.clone(), rule_index);
+            let breached = rule.op.breaches(value, rule.value);
+            let already_active = state.read().unwrap().contains_key(&key);
+
+            if breached && !already_active {
+                let alert = ActiveAlert {
+                    device_id: message.device_id.clone(),
+                    metric: rule.metric.clone(),
+                    value,
+                    threshold: rule.value,
+                    fired_at: message.timestamp,
+                };
+
+                state.write().unwrap().insert(key, alert.clone());
+                dispatch_webhook(&client, &config, &rule.op, &alert).await;
+            } else if !breached && already_active {
+                state.write().unwrap().remove(&key);
+                debug!("Alert cleared for {}/{}", message.device_id, rule.metric);
+            }
+        }
+    }
+
+    error!("Alerting task ended");
+}
+
+async fn dispatch_webhook(client: &reqwest::Client, config: &Config, op: &AlertOp, alert: &ActiveAlert) {
+    let Some(webhook_url) = &config.alert_webhook_url else {
+        return;
+    };
+
+    let op_str = match op {
+        AlertOp::Gt => "gt",
+        AlertOp::Lt => "lt",
+    };
+
+    let payload = WebhookPayload {
+        device_id: &alert.device_id,
+        metric: &alert.metric,
+        op: op_str,
+        threshold: alert.threshold,
+        value: alert.value,
+        fired_at: alert.fired_at,
+    };
+
+    info!("Alert fired: {}/{} {} {} (value {})", alert.device_id, alert.metric, op_str, alert.threshold, alert.value);
+
+    if let Err(e) = client.post(webhook_url).json(&payload).send().await {
+        error!("Failed to dispatch alert webhook: {}", e);
+    }
+}
@@ -1,22 +1,47 @@
 
 use axum::{
-    extract::{Query, State},
+    extract::{Path, Query, State},
     http::StatusCode,
-    response::Json,
-    routing::get,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Json,
+    },
+    routing::{get, post},
     Router,
 };
+use futures::Stream;
+use rumqttc::{AsyncClient, QoS};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::convert::Infallible;
 use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
 use tracing::{error, debug};
 
-use crate::db::{Database, AggregatedReading};
+use crate::alerting::{AlertState, ActiveAlert};
+use crate::db::{Database, AggregatedReading, Reading};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db: Arc<Database>,
+    pub mqtt_client: AsyncClient,
+    pub reading_tx: broadcast::Sender<Reading>,
+    pub alert_state: AlertState,
+}
 
 #[derive(Debug, Deserialize)]
 pub struct ReadingsQuery {
     device_id: String,
+    metric: String,
     range: Option<String>,
+    fill: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetricsQuery {
+    device_id: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -44,20 +69,24 @@ impl<T> ApiResponse<T> {
     }
 }
 
-pub fn create_router(db: Arc<Database>) -> Router {
+pub fn create_router(state: AppState) -> Router {
     Router::new()
         .route("/api/devices", get(get_devices))
+        .route("/api/devices/:device_id/command", post(send_command))
         .route("/api/readings", get(get_readings))
+        .route("/api/metrics", get(get_metrics))
+        .route("/api/stream", get(stream_readings))
+        .route("/api/alerts", get(get_alerts))
         .route("/api/health", get(health_check))
-        .with_state(db)
+        .with_state(state)
 }
 
 async fn get_devices(
-    State(db): State<Arc<Database>>,
+    State(state): State<AppState>,
 ) -> Result<Json<ApiResponse<Vec<String>>>, StatusCode> {
     debug!("GET /api/devices");
-    
-    match db.get_devices().await {
+
+    match state.db.get_devices().await {
         Ok(devices) => Ok(Json(ApiResponse::success(devices))),
         Err(e) => {
             error!("Failed to get devices: {}", e);
@@ -68,20 +97,24 @@ async fn get_devices(
 
 async fn get_readings(
     Query(params): Query<ReadingsQuery>,
-    State(db): State<Arc<Database>>,
+    State(state): State<AppState>,
 ) -> Result<Json<ApiResponse<Vec<AggregatedReading>>>, StatusCode> {
-    debug!("GET /api/readings?device_id={}&range={:?}", params.device_id, params.range);
-    
+    debug!(
+        "GET /api/readings?device_id={}&metric={}&range={:?}&fill={:?}",
+        params.device_id, params.metric, params.range, params.fill
+    );
+
     let range = params.range.as_deref().unwrap_or("1d");
-    
+    let fill = params.fill.unwrap_or(false);
+
     // Validate range parameter
     if !["1d", "1w", "1m", "6m", "1y"].contains(&range) {
         return Ok(Json(ApiResponse::error(
             "Invalid range. Must be one of: 1d, 1w, 1m, 6m, 1y".to_string()
         )));
     }
-    
-    match db.get_readings(&params.device_id, range).await {
+
+    match state.db.get_readings(&params.device_id, &params.metric, range, fill).await {
         Ok(readings) => Ok(Json(ApiResponse::success(readings))),
         Err(e) => {
             error!("Failed to get readings: {}", e);
@@ -90,12 +123,75 @@ async fn get_readings(
     }
 }
 
+async fn get_metrics(
+    Query(params): Query<MetricsQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<Vec<String>>>, StatusCode> {
+    debug!("GET /api/metrics?device_id={}", params.device_id);
+
+    match state.db.get_metrics(&params.device_id).await {
+        Ok(metrics) => Ok(Json(ApiResponse::success(metrics))),
+        Err(e) => {
+            error!("Failed to get metrics: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    device_id: Option<String>,
+}
+
+/// Pushes new readings to the client as they arrive, instead of making it poll
+/// `/api/readings`. Backed by the broadcast channel that `db::listener_task` feeds from
+/// Postgres `LISTEN/NOTIFY`.
+async fn stream_readings(
+    Query(params): Query<StreamQuery>,
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    debug!("GET /api/stream?device_id={:?}", params.device_id);
+
+    let rx = state.reading_tx.subscribe();
+    let stream = BroadcastStream::new(rx).filter_map(move |msg| {
+        let reading = match msg {
+            Ok(reading) => reading,
+            Err(_) => return None, // lagged behind the broadcast buffer; skip ahead
+        };
+
+        if let Some(device_id) = &params.device_id {
+            if &reading.device_id != device_id {
+                return None;
+            }
+        }
+
+        match serde_json::to_string(&reading) {
+            Ok(json) => Some(Ok(Event::default().event("reading").data(json))),
+            Err(e) => {
+                error!("Failed to serialize reading for SSE: {}", e);
+                None
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn get_alerts(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<Vec<ActiveAlert>>>, StatusCode> {
+    debug!("GET /api/alerts");
+
+    let alerts = state.alert_state.read().unwrap().values().cloned().collect();
+    Ok(Json(ApiResponse::success(alerts)))
+}
+
 async fn health_check(
-    State(db): State<Arc<Database>>,
+    State(state): State<AppState>,
 ) -> Result<Json<ApiResponse<HashMap<String, String>>>, StatusCode> {
     debug!("GET /api/health");
-    
-    match db.health_check().await {
+
+    match state.db.health_check().await {
         Ok(_) => {
             let mut status = HashMap::new();
             status.insert("status".to_string(), "healthy".to_string());
@@ -108,3 +204,52 @@ async fn health_check(
         }
     }
 }
+
+#[derive(Debug, Deserialize)]
+pub struct CommandRequest {
+    payload: serde_json::Value,
+    qos: Option<u8>,
+    retain: Option<bool>,
+}
+
+/// `rumqttc`'s `AsyncClient::publish` only queues the request -- the MQTT packet id is
+/// assigned by the event loop and isn't known until it sends the request, and for the default QoS 0 there's no
+/// `PubAck` at all. So this only confirms the command was handed to the MQTT client, not that
+/// a device (or even the broker) received it; it does not return a packet id to correlate
+/// against, since there's nothing real to correlate yet.
+async fn send_command(
+    Path(device_id): Path<String>,
+    State(state): State<AppState>,
+    Json(command): Json<CommandRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<()>>), StatusCode> {
+    debug!("POST /api/devices/{}/command", device_id);
+
+    let qos = match command.qos.unwrap_or(0) {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        2 => QoS::ExactlyOnce,
+        other => {
+            return Ok((StatusCode::BAD_REQUEST, Json(ApiResponse::error(format!(
+                "Invalid qos {}. Must be 0, 1, or 2", other
+            )))));
+        }
+    };
+    let retain = command.retain.unwrap_or(false);
+    let topic = format!("sensors/{}/commands", device_id);
+
+    let payload = match serde_json::to_vec(&command.payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!("Failed to serialize command payload: {}", e);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+
+    match state.mqtt_client.publish(&topic, qos, retain, payload).await {
+        Ok(()) => Ok((StatusCode::ACCEPTED, Json(ApiResponse::success(())))),
+        Err(e) => {
+            error!("Failed to publish command to {}: {}", topic, e);
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use anyhow::Result;
+use sqlx::postgres::PgListener;
 use sqlx::{PgPool, Row};
 use std::sync::Arc;
-use tokio::sync::mpsc;
-use tracing::{info, error, debug};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{info, error, warn, debug};
 use std::time::Duration;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -14,19 +15,18 @@ pub struct Database {
     pool: PgPool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Reading {
     pub device_id: String,
     pub ts: DateTime<Utc>,
-    pub temperature_c: Option<f64>,
-    pub humidity_pct: Option<f64>,
+    pub metric: String,
+    pub value: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AggregatedReading {
     pub ts: DateTime<Utc>,
-    pub avg_temperature_c: Option<f64>,
-    pub avg_humidity_pct: Option<f64>,
+    pub avg_value: Option<f64>,
 }
 
 impl Database {
@@ -48,22 +48,45 @@ impl Database {
         sqlx::query("CREATE INDEX IF NOT EXISTS idx_readings_device_ts ON readings (device_id, ts)")
             .execute(&self.pool)
             .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_readings_device_metric_ts ON readings (device_id, metric, ts)")
+            .execute(&self.pool)
+            .await?;
         Ok(())
     }
 
     pub async fn insert_reading(&self, message: &TelemetryMessage) -> Result<()> {
-        sqlx::query(
-            r#"
-            INSERT INTO readings (device_id, ts, temperature_c, humidity_pct)
-            VALUES ($1, $2, $3, $4)
-            "#
-        )
-        .bind(&message.device_id)
-        .bind(&message.timestamp)    // Ensure message.timestamp is UTC; if optional, default on server
-        .bind(&message.temperature_c)
-        .bind(&message.humidity_pct)
-        .execute(&self.pool)
-        .await?;
+        self.insert_readings_batch(std::slice::from_ref(message)).await
+    }
+
+    /// Flushes a batch of messages as one or more multi-row INSERTs, so high-rate ingestion
+    /// doesn't pay a round-trip per reading. Each metric in a message becomes its own row, so a
+    /// wide payload (many metrics per message) can expand a small number of messages into a lot
+    /// of rows -- the rows are chunked to `MAX_INSERT_ROWS` so no single statement ever binds
+    /// more than Postgres's 65535-parameter ceiling (4 params per row here).
+    pub async fn insert_readings_batch(&self, messages: &[TelemetryMessage]) -> Result<()> {
+        let rows: Vec<(&str, DateTime<Utc>, &str, f64)> = messages
+            .iter()
+            .flat_map(|message| {
+                message.metrics.iter().map(move |(metric, value)| {
+                    (message.device_id.as_str(), message.timestamp, metric.as_str(), *value)
+                })
+            })
+            .collect();
+
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        for chunk in rows.chunks(MAX_INSERT_ROWS) {
+            let mut builder: sqlx::QueryBuilder<sqlx::Postgres> =
+                sqlx::QueryBuilder::new("INSERT INTO readings (device_id, ts, metric, value) ");
+
+            builder.push_values(chunk, |mut row, (device_id, ts, metric, value)| {
+                row.push_bind(*device_id).push_bind(*ts).push_bind(*metric).push_bind(*value);
+            });
+
+            builder.build().execute(&self.pool).await?;
+        }
 
         Ok(())
     }
@@ -76,7 +99,16 @@ impl Database {
         Ok(rows.into_iter().map(|row| row.get::<String, _>("device_id")).collect())
     }
 
-    pub async fn get_readings(&self, device_id: &str, range: &str) -> Result<Vec<AggregatedReading>> {
+    pub async fn get_metrics(&self, device_id: &str) -> Result<Vec<String>> {
+        let rows = sqlx::query("SELECT DISTINCT metric FROM readings WHERE device_id = $1 ORDER BY metric")
+            .bind(device_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(|row| row.get::<String, _>("metric")).collect())
+    }
+
+    pub async fn get_readings(&self, device_id: &str, metric: &str, range: &str, fill: bool) -> Result<Vec<AggregatedReading>> {
         // Map UI ranges to window + bucket
         // Using text -> interval cast in SQL for safe parameterization.
         let (window_interval, bucket_interval) = match range {
@@ -88,32 +120,67 @@ impl Database {
             _ => ("1 day", "5 minutes"),
         };
 
-        // Postgres 15+: date_bin for clean bucketing.
-        // Note: This wonâ€™t generate empty buckets (only bins that have data).
-        // If you need gap-filling, we can switch to generate_series.
-        let rows = sqlx::query(
-            r#"
-            SELECT
-                date_bin($2::interval, ts, '1970-01-01 00:00:00+00'::timestamptz) AS ts,
-                AVG(temperature_c) AS avg_temperature_c,
-                AVG(humidity_pct)  AS avg_humidity_pct
-            FROM readings
-            WHERE device_id = $3
-              AND ts >= now() - $1::interval
-            GROUP BY ts
-            ORDER BY ts
-            "#
-        )
-        .bind(window_interval)   // $1::interval
-        .bind(bucket_interval)   // $2::interval
-        .bind(device_id)         // $3
-        .fetch_all(&self.pool)
-        .await?;
+        let rows = if fill {
+            // Gap-filled: LEFT JOIN the bucketed averages against every bucket in the window so
+            // outages show up as NULL averages instead of date_bin's usual "missing bucket",
+            // which made charts draw a misleading straight line across the gap.
+            sqlx::query(
+                r#"
+                WITH bucketed AS (
+                    SELECT
+                        date_bin($2::interval, ts, '1970-01-01 00:00:00+00'::timestamptz) AS ts,
+                        AVG(value) AS avg_value
+                    FROM readings
+                    WHERE device_id = $3
+                      AND metric = $4
+                      AND ts >= now() - $1::interval
+                    GROUP BY 1
+                )
+                SELECT
+                    series.ts AS ts,
+                    bucketed.avg_value AS avg_value
+                FROM generate_series(
+                    date_bin($2::interval, now() - $1::interval, '1970-01-01 00:00:00+00'::timestamptz),
+                    date_bin($2::interval, now(), '1970-01-01 00:00:00+00'::timestamptz),
+                    $2::interval
+                ) AS series(ts)
+                LEFT JOIN bucketed ON bucketed.ts = series.ts
+                ORDER BY series.ts
+                "#
+            )
+            .bind(window_interval)   // $1::interval
+            .bind(bucket_interval)   // $2::interval
+            .bind(device_id)         // $3
+            .bind(metric)            // $4
+            .fetch_all(&self.pool)
+            .await?
+        } else {
+            // Postgres 15+: date_bin for clean bucketing.
+            // Note: this only emits buckets that have data. Pass fill=true for gap-filled buckets.
+            sqlx::query(
+                r#"
+                SELECT
+                    date_bin($2::interval, ts, '1970-01-01 00:00:00+00'::timestamptz) AS ts,
+                    AVG(value) AS avg_value
+                FROM readings
+                WHERE device_id = $3
+                  AND metric = $4
+                  AND ts >= now() - $1::interval
+                GROUP BY 1
+                ORDER BY ts
+                "#
+            )
+            .bind(window_interval)   // $1::interval
+            .bind(bucket_interval)   // $2::interval
+            .bind(device_id)         // $3
+            .bind(metric)            // $4
+            .fetch_all(&self.pool)
+            .await?
+        };
 
         let readings = rows.into_iter().map(|row| AggregatedReading {
             ts: row.get::<DateTime<Utc>, _>("ts"),
-            avg_temperature_c: row.get::<Option<f64>, _>("avg_temperature_c"),
-            avg_humidity_pct: row.get::<Option<f64>, _>("avg_humidity_pct"),
+            avg_value: row.get::<Option<f64>, _>("avg_value"),
         }).collect();
 
         Ok(readings)
@@ -133,20 +200,105 @@ impl Database {
     }
 }
 
+// Postgres caps a single statement at 65535 bound parameters. `insert_readings_batch` binds 4
+// params per row, so MAX_INSERT_ROWS (4000 rows = 16000 params) keeps every INSERT well under
+// that ceiling even if a batch somehow grows past WRITER_BATCH_ROWS.
+const MAX_INSERT_ROWS: usize = 4000;
+
+// Each buffered message can expand to many rows -- one per metric -- once it reaches
+// `insert_readings_batch`, so the writer flushes on accumulated row count rather than message
+// count. This stays comfortably under MAX_INSERT_ROWS for the common case of a single flush.
+const WRITER_BATCH_ROWS: usize = 2000;
+const WRITER_FLUSH_INTERVAL: Duration = Duration::from_millis(200);
+
 pub async fn writer_task(db: Arc<Database>, mut rx: mpsc::UnboundedReceiver<TelemetryMessage>) {
     info!("Database writer task started");
 
-    while let Some(message) = rx.recv().await {
-        debug!("Writing telemetry message: {:?}", message);
+    let mut batch = Vec::new();
+    let mut batch_rows = 0usize;
+    let mut flush_interval = tokio::time::interval(WRITER_FLUSH_INTERVAL);
+    flush_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            message = rx.recv() => {
+                match message {
+                    Some(message) => {
+                        debug!("Buffered telemetry message: {:?}", message);
+                        batch_rows += message.metrics.len();
+                        batch.push(message);
 
-        if let Err(e) = db.insert_reading(&message).await {
-            error!("Failed to insert reading: {}", e);
+                        if batch_rows >= WRITER_BATCH_ROWS {
+                            flush_batch(&db, &mut batch, &mut batch_rows).await;
+                        }
+                    }
+                    None => {
+                        flush_batch(&db, &mut batch, &mut batch_rows).await;
+                        break;
+                    }
+                }
+            }
+            _ = flush_interval.tick() => {
+                flush_batch(&db, &mut batch, &mut batch_rows).await;
+            }
         }
     }
 
     error!("Database writer task ended");
 }
 
+async fn flush_batch(db: &Arc<Database>, batch: &mut Vec<TelemetryMessage>, batch_rows: &mut usize) {
+    if batch.is_empty() {
+        return;
+    }
+
+    debug!("Flushing {} messages ({} rows)", batch.len(), batch_rows);
+
+    if let Err(e) = db.insert_readings_batch(batch).await {
+        error!("Failed to insert batch, retrying once: {}", e);
+
+        if let Err(e) = db.insert_readings_batch(batch).await {
+            error!("Retry failed, dropping batch of {} messages ({} rows): {}", batch.len(), batch_rows, e);
+        }
+    }
+
+    batch.clear();
+    *batch_rows = 0;
+}
+
+/// Listens for `new_reading` Postgres notifications (fired by the `reading_notify_trigger`
+/// migration) and forwards each one onto a broadcast channel that the SSE route subscribes to.
+pub async fn listener_task(database_url: String, tx: broadcast::Sender<Reading>) {
+    info!("Postgres notification listener task started");
+
+    loop {
+        if let Err(e) = run_listener(&database_url, &tx).await {
+            error!("Postgres listener error: {}", e);
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    }
+}
+
+async fn run_listener(database_url: &str, tx: &broadcast::Sender<Reading>) -> Result<()> {
+    let mut listener = PgListener::connect(database_url).await?;
+    listener.listen("new_reading").await?;
+    info!("Listening for new_reading notifications");
+
+    loop {
+        let notification = listener.recv().await?;
+
+        match serde_json::from_str::<Reading>(notification.payload()) {
+            Ok(reading) => {
+                // No subscribers is fine -- just means nobody has the dashboard open.
+                let _ = tx.send(reading);
+            }
+            Err(e) => {
+                warn!("Failed to parse new_reading payload: {}", e);
+            }
+        }
+    }
+}
+
 pub async fn cleanup_task(db: Arc<Database>) {
     info!("Database cleanup task started");
 
@@ -1,9 +1,11 @@
 
 use anyhow::Result;
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
 use tracing::{info, error};
 
+mod alerting;
 mod config;
 mod mqtt;
 mod db;
@@ -16,8 +18,7 @@ use db::Database;
 #[derive(Debug, Clone)]
 pub struct TelemetryMessage {
     pub device_id: String,
-    pub temperature_c: Option<f64>,
-    pub humidity_pct: Option<f64>,
+    pub metrics: HashMap<String, f64>,
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
@@ -41,14 +42,38 @@ async fn main() -> Result<()> {
 
     let db = Arc::new(db);
 
-    // Create channel for MQTT messages
-    let (tx, rx) = mpsc::unbounded_channel::<TelemetryMessage>();
+    // Create channel for MQTT messages, then tee it into a writer stream and an alerting
+    // stream so alerting runs independently of DB persistence.
+    let (tx, mut rx) = mpsc::unbounded_channel::<TelemetryMessage>();
+    let (db_tx, db_rx) = mpsc::unbounded_channel::<TelemetryMessage>();
+    let (alert_tx, alert_rx) = mpsc::unbounded_channel::<TelemetryMessage>();
+
+    let tee_task = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            if db_tx.send(message.clone()).is_err() {
+                error!("Database writer channel closed");
+            }
+            if alert_tx.send(message).is_err() {
+                error!("Alerting channel closed");
+            }
+        }
+    });
 
     // Start database writer task
     let db_writer = {
         let db = Arc::clone(&db);
         tokio::spawn(async move {
-            db::writer_task(db, rx).await;
+            db::writer_task(db, db_rx).await;
+        })
+    };
+
+    // Start alerting task
+    let alert_state: alerting::AlertState = Arc::new(std::sync::RwLock::new(std::collections::HashMap::new()));
+    let alert_task = {
+        let config = config.clone();
+        let alert_state = Arc::clone(&alert_state);
+        tokio::spawn(async move {
+            alerting::run(config, alert_rx, alert_state).await;
         })
     };
 
@@ -60,20 +85,27 @@ async fn main() -> Result<()> {
         })
     };
 
-    // Start MQTT client
-    let mqtt_task = {
-        let config = config.clone();
+    // Broadcast channel for pushing new readings (via Postgres LISTEN/NOTIFY) out over SSE
+    let (reading_tx, _) = broadcast::channel::<db::Reading>(256);
+
+    // Start Postgres notification listener task
+    let listener_task = {
+        let reading_tx = reading_tx.clone();
+        let pg_url = config.pg_url.clone();
         tokio::spawn(async move {
-            mqtt::client_task(config, tx).await;
+            db::listener_task(pg_url, reading_tx).await;
         })
     };
 
+    // Start MQTT client and keep the handle alive so the web server can publish commands
+    let (mqtt_client, mqtt_task) = mqtt::client_task(config.clone(), tx).await?;
+
     // Start web server
     let web_task = {
         let config = config.clone();
         let db = Arc::clone(&db);
         tokio::spawn(async move {
-            web::serve(config, db).await;
+            web::serve(config, db, mqtt_client, reading_tx, alert_state).await;
         })
     };
 
@@ -81,8 +113,11 @@ async fn main() -> Result<()> {
 
     // Wait for any task to complete (they should run forever)
     tokio::select! {
+        _ = tee_task => error!("Telemetry tee task ended"),
         _ = db_writer => error!("Database writer task ended"),
+        _ = alert_task => error!("Alerting task ended"),
         _ = cleanup_task => error!("Cleanup task ended"),
+        _ = listener_task => error!("Postgres listener task ended"),
         _ = mqtt_task => error!("MQTT task ended"),
         _ = web_task => error!("Web task ended"),
     }
@@ -1,54 +1,99 @@
 
-use anyhow::Result;
-use rumqttc::{AsyncClient, MqttOptions, QoS, Event, Packet};
+use anyhow::{anyhow, Context, Result};
+use rumqttc::{AsyncClient, MqttOptions, QoS, Event, Packet, Transport, TlsConfiguration};
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 use tracing::{info, error, warn, debug};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
 use std::time::Duration;
+use url::Url;
 
 use crate::{Config, TelemetryMessage};
 
+/// Payload shape is collectd-style: an arbitrary set of named numeric metrics plus an
+/// optional timestamp, e.g. `{"temperature_c": 21.5, "pressure_hpa": 1013.2, "ts": "..."}`.
 #[derive(Debug, Deserialize, Serialize)]
-struct MqttPayload {
-    temperature_c: Option<f64>,
-    humidity_pct: Option<f64>,
-    ts: Option<String>,
-}
+#[serde(transparent)]
+struct MqttPayload(std::collections::HashMap<String, serde_json::Value>);
 
-pub async fn client_task(config: Config, tx: mpsc::UnboundedSender<TelemetryMessage>) {
-    loop {
-        if let Err(e) = run_mqtt_client(&config, &tx).await {
-            error!("MQTT client error: {}", e);
-            warn!("Retrying MQTT connection in 5 seconds...");
-            tokio::time::sleep(Duration::from_secs(5)).await;
-        }
+impl MqttPayload {
+    fn ts(&self) -> Option<&str> {
+        self.0.get("ts").and_then(serde_json::Value::as_str)
+    }
+
+    fn metrics(&self) -> std::collections::HashMap<String, f64> {
+        self.0
+            .iter()
+            .filter(|(key, _)| key.as_str() != "ts")
+            .filter_map(|(key, value)| value.as_f64().map(|v| (key.clone(), v)))
+            .collect()
     }
 }
 
-async fn run_mqtt_client(config: &Config, tx: &mpsc::UnboundedSender<TelemetryMessage>) -> Result<()> {
-    let mut mqttoptions = MqttOptions::new(&config.mqtt_client_id, "localhost", 1883);
+/// Builds the MQTT client and subscribes to the configured topic filter, then hands back the
+/// `AsyncClient` (kept alive for publishing commands) alongside a background task that drives
+/// the event loop for the lifetime of the process.
+pub async fn client_task(
+    config: Config,
+    tx: mpsc::UnboundedSender<TelemetryMessage>,
+) -> Result<(AsyncClient, tokio::task::JoinHandle<()>)> {
+    let (client, eventloop) = build_client(&config).await?;
+    let subscribe_client = client.clone();
+    let topic_filter = config.mqtt_topic_filter.clone();
+    let handle = tokio::spawn(poll_event_loop(eventloop, subscribe_client, topic_filter, tx));
+    Ok((client, handle))
+}
+
+async fn build_client(config: &Config) -> Result<(AsyncClient, rumqttc::EventLoop)> {
+    let broker = BrokerUrl::parse(&config.mqtt_broker_url)?;
+
+    let mut mqttoptions = MqttOptions::new(&config.mqtt_client_id, &broker.host, broker.port);
     mqttoptions.set_keep_alive(Duration::from_secs(30));
     mqttoptions.set_clean_session(true);
 
-    let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
-    
+    if let (Some(username), Some(password)) = (&broker.username, &broker.password) {
+        mqttoptions.set_credentials(username, password);
+    }
+
+    if broker.tls {
+        mqttoptions.set_transport(Transport::tls_with_config(build_tls_config(config)?));
+    }
+
+    let (client, eventloop) = AsyncClient::new(mqttoptions, 10);
+
     info!("Connecting to MQTT broker...");
-    
-    // Subscribe to the topic
-    client.subscribe(&config.mqtt_topic_filter, QoS::AtMostOnce).await?;
-    info!("Subscribed to topic: {}", config.mqtt_topic_filter);
 
+    // The actual subscribe happens in `poll_event_loop` on every `ConnAck`, including the first
+    // one -- that's the only way to also re-subscribe after a reconnect.
+    Ok((client, eventloop))
+}
+
+/// Drives the event loop for as long as the process runs. `rumqttc` reconnects on the next
+/// `poll()` call after an error, so we keep polling through failures rather than rebuilding the
+/// client -- that's what lets `AsyncClient` stay valid for the downlink publish route.
+///
+/// `set_clean_session(true)` means the broker forgets our subscriptions across a reconnect, so
+/// `client` and `topic_filter` are threaded through here: every `ConnAck` (the first connect and
+/// any later reconnect) re-issues the subscription.
+async fn poll_event_loop(
+    mut eventloop: rumqttc::EventLoop,
+    client: AsyncClient,
+    topic_filter: String,
+    tx: mpsc::UnboundedSender<TelemetryMessage>,
+) {
     loop {
         match eventloop.poll().await {
             Ok(Event::Incoming(Packet::Publish(publish))) => {
                 debug!("Received MQTT message on topic: {}", publish.topic);
-                
+
                 // Extract device_id from topic (sensors/{device_id}/telemetry)
                 let device_id = extract_device_id(&publish.topic);
-                
+
                 match serde_json::from_slice::<MqttPayload>(&publish.payload) {
                     Ok(payload) => {
-                        let timestamp = if let Some(ts_str) = &payload.ts {
+                        let timestamp = if let Some(ts_str) = payload.ts() {
                             chrono::DateTime::parse_from_rfc3339(ts_str)
                                 .map(|dt| dt.with_timezone(&chrono::Utc))
                                 .unwrap_or_else(|_| chrono::Utc::now())
@@ -58,8 +103,7 @@ async fn run_mqtt_client(config: &Config, tx: &mpsc::UnboundedSender<TelemetryMe
 
                         let message = TelemetryMessage {
                             device_id,
-                            temperature_c: payload.temperature_c,
-                            humidity_pct: payload.humidity_pct,
+                            metrics: payload.metrics(),
                             timestamp,
                         };
 
@@ -74,11 +118,17 @@ async fn run_mqtt_client(config: &Config, tx: &mpsc::UnboundedSender<TelemetryMe
             }
             Ok(Event::Incoming(Packet::ConnAck(_))) => {
                 info!("Connected to MQTT broker");
+                if let Err(e) = client.subscribe(&topic_filter, QoS::AtMostOnce).await {
+                    error!("Failed to (re)subscribe to {}: {}", topic_filter, e);
+                } else {
+                    info!("Subscribed to topic: {}", topic_filter);
+                }
             }
             Ok(_) => {}
             Err(e) => {
                 error!("MQTT connection error: {}", e);
-                return Err(e.into());
+                warn!("Retrying MQTT connection in 5 seconds...");
+                tokio::time::sleep(Duration::from_secs(5)).await;
             }
         }
     }
@@ -91,3 +141,156 @@ fn extract_device_id(topic: &str) -> String {
         .unwrap_or("unknown")
         .to_string()
 }
+
+struct BrokerUrl {
+    host: String,
+    port: u16,
+    tls: bool,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl BrokerUrl {
+    fn parse(raw: &str) -> Result<Self> {
+        let url = Url::parse(raw).context("invalid MQTT_BROKER_URL")?;
+
+        let tls = match url.scheme() {
+            "mqtt" => false,
+            "mqtts" => true,
+            scheme => return Err(anyhow!("unsupported MQTT broker scheme: {}", scheme)),
+        };
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("MQTT_BROKER_URL is missing a host"))?
+            .to_string();
+        let port = url.port().unwrap_or(if tls { 8883 } else { 1883 });
+
+        let mut username = None;
+        let mut password = None;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "username" => username = Some(value.into_owned()),
+                "password" => password = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+
+        Ok(BrokerUrl { host, port, tls, username, password })
+    }
+}
+
+/// Builds a rustls client config for `mqtts://` connections. Loads the OS trust store by
+/// default, and additionally trusts `MQTT_CA_CERT` and presents a client certificate/key
+/// (`MQTT_CLIENT_CERT` / `MQTT_CLIENT_KEY`) for mutual-TLS brokers when configured.
+fn build_tls_config(config: &Config) -> Result<TlsConfiguration> {
+    let mut root_store = rustls::RootCertStore::empty();
+
+    for cert in rustls_native_certs::load_native_certs().context("failed to load OS trust store")? {
+        root_store
+            .add(&rustls::Certificate(cert.0))
+            .context("failed to add native certificate to trust store")?;
+    }
+
+    if let Some(ca_path) = &config.mqtt_ca_cert {
+        for cert in read_certs(ca_path)? {
+            root_store
+                .add(&cert)
+                .context("failed to add MQTT_CA_CERT to trust store")?;
+        }
+    }
+
+    let builder = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store);
+
+    let client_config = match (&config.mqtt_client_cert, &config.mqtt_client_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let certs = read_certs(cert_path)?;
+            let key = read_private_key(key_path)?;
+            builder
+                .with_client_auth_cert(certs, key)
+                .context("invalid MQTT_CLIENT_CERT / MQTT_CLIENT_KEY pair")?
+        }
+        _ => builder.with_no_client_auth(),
+    };
+
+    Ok(TlsConfiguration::Rustls(Arc::new(client_config)))
+}
+
+fn read_certs(path: &str) -> Result<Vec<rustls::Certificate>> {
+    let mut reader = BufReader::new(File::open(path).with_context(|| format!("failed to open {}", path))?);
+    let certs = rustls_pemfile::certs(&mut reader).with_context(|| format!("failed to parse certificates in {}", path))?;
+    Ok(certs.into_iter().map(rustls::Certificate).collect())
+}
+
+fn read_private_key(path: &str) -> Result<rustls::PrivateKey> {
+    let mut reader = BufReader::new(File::open(path).with_context(|| format!("failed to open {}", path))?);
+    let key = rustls_pemfile::pkcs8_private_keys(&mut reader)
+        .with_context(|| format!("failed to parse private key in {}", path))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("no PKCS#8 private key found in {}", path))?;
+    Ok(rustls::PrivateKey(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broker_url_parses_plain_mqtt() {
+        let broker = BrokerUrl::parse("mqtt://localhost:1883").unwrap();
+        assert_eq!(broker.host, "localhost");
+        assert_eq!(broker.port, 1883);
+        assert!(!broker.tls);
+        assert_eq!(broker.username, None);
+        assert_eq!(broker.password, None);
+    }
+
+    #[test]
+    fn broker_url_parses_mqtts_with_default_port() {
+        let broker = BrokerUrl::parse("mqtts://broker.example.com").unwrap();
+        assert_eq!(broker.host, "broker.example.com");
+        assert_eq!(broker.port, 8883);
+        assert!(broker.tls);
+    }
+
+    #[test]
+    fn broker_url_parses_credentials_from_query_string() {
+        let broker = BrokerUrl::parse("mqtt://broker.example.com:1884?username=pi&password=hunter2").unwrap();
+        assert_eq!(broker.port, 1884);
+        assert_eq!(broker.username.as_deref(), Some("pi"));
+        assert_eq!(broker.password.as_deref(), Some("hunter2"));
+    }
+
+    #[test]
+    fn broker_url_rejects_unsupported_scheme() {
+        assert!(BrokerUrl::parse("http://broker.example.com").is_err());
+    }
+
+    #[test]
+    fn mqtt_payload_metrics_drops_ts_and_keeps_numeric_fields() {
+        let payload: MqttPayload = serde_json::from_str(
+            r#"{"temperature_c": 21.5, "pressure_hpa": 1013.2, "ts": "2024-01-01T00:00:00Z"}"#
+        ).unwrap();
+
+        let metrics = payload.metrics();
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics.get("temperature_c"), Some(&21.5));
+        assert_eq!(metrics.get("pressure_hpa"), Some(&1013.2));
+        assert_eq!(payload.ts(), Some("2024-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn mqtt_payload_metrics_ignores_non_numeric_fields() {
+        let payload: MqttPayload = serde_json::from_str(
+            r#"{"device_label": "kitchen", "battery_v": 3.7}"#
+        ).unwrap();
+
+        let metrics = payload.metrics();
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics.get("battery_v"), Some(&3.7));
+        assert_eq!(payload.ts(), None);
+    }
+}
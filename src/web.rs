@@ -1,14 +1,25 @@
 
 use axum::Router;
+use rumqttc::AsyncClient;
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use tower_http::services::ServeDir;
 use tracing::{info, error};
 
-use crate::{Config, db::Database, api};
+use crate::{alerting::AlertState, Config, db::{Database, Reading}, api};
+use crate::api::AppState;
+
+pub async fn serve(
+    config: Config,
+    db: Arc<Database>,
+    mqtt_client: AsyncClient,
+    reading_tx: broadcast::Sender<Reading>,
+    alert_state: AlertState,
+) {
+    let state = AppState { db, mqtt_client, reading_tx, alert_state };
 
-pub async fn serve(config: Config, db: Arc<Database>) {
     let app = Router::new()
-        .merge(api::create_router(db))
+        .merge(api::create_router(state))
         .nest_service("/", ServeDir::new("static"));
 
     let listener = match tokio::net::TcpListener::bind(&config.app_addr).await {